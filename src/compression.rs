@@ -0,0 +1,312 @@
+//
+// On-the-fly response compression, negotiated through `Accept-Encoding`.
+//
+// This is wired up from `DavInner::handle2`: after a `GET` or `PROPFIND`
+// response has been produced we look at the request's `Accept-Encoding`
+// header, pick the best codec the client and the server both understand,
+// and wrap the outgoing `Body` stream in a streaming encoder.
+//
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression as FlateLevel;
+use futures::stream::Stream;
+use http::{header, Response, StatusCode};
+use std::io::Write;
+
+use crate::body::Body;
+
+/// Which response-body compression codecs the handler may apply.
+///
+/// The default (`None`) leaves bodies untouched. `Auto` enables every codec
+/// and lets the client's `Accept-Encoding` header decide which one is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Never compress (the default).
+    None,
+    /// Negotiate the best supported codec per request.
+    Auto,
+    /// Only ever use gzip, when the client accepts it.
+    Gzip,
+    /// Only ever use deflate, when the client accepts it.
+    Deflate,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::None
+    }
+}
+
+// The codec we settled on for a single response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Gzip,
+    Deflate,
+}
+
+impl Codec {
+    fn token(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Deflate => "deflate",
+        }
+    }
+}
+
+/// Negotiate and, if appropriate, apply compression to a finished response.
+///
+/// `mode` is the configured policy, `min_size` the smallest body worth
+/// compressing, `accept_encoding` the raw request header (if any) and
+/// `had_range` whether the request carried a `Range` header. When compression
+/// does not apply the body is left as is; `Vary: Accept-Encoding` is still
+/// appended unless `mode` is `None`, the request had a range, the response is
+/// a `206`, or it already carries a `Content-Encoding`.
+pub(crate) fn compress(
+    mode: Compression,
+    min_size: u64,
+    accept_encoding: Option<&str>,
+    had_range: bool,
+    mut resp: Response<Body>,
+) -> Response<Body> {
+    if mode == Compression::None {
+        return resp;
+    }
+
+    // Never touch range requests / partial responses: the byte ranges the
+    // client asked for refer to the identity representation.
+    if had_range || resp.status() == StatusCode::PARTIAL_CONTENT {
+        return resp;
+    }
+
+    // Already encoded by the handler? Leave it be.
+    if resp.headers().contains_key(header::CONTENT_ENCODING) {
+        return resp;
+    }
+
+    // Apply the minimum-size gate consistently. A body whose length we can't
+    // read up front can't be shown to clear the threshold, so we leave it
+    // uncompressed rather than compress a possibly-tiny stream.
+    let len = resp
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match len {
+        Some(len) if len >= min_size => {},
+        _ => {
+            // Still advertise that the representation varies on the header, so
+            // shared caches key correctly.
+            append_vary(&mut resp);
+            return resp;
+        },
+    }
+
+    let codec = match pick_codec(mode, accept_encoding) {
+        Some(c) => c,
+        None => {
+            append_vary(&mut resp);
+            return resp;
+        },
+    };
+
+    // Switch to chunked: the compressed length is not known up front.
+    resp.headers_mut().remove(header::CONTENT_LENGTH);
+    resp.headers_mut()
+        .insert(header::CONTENT_ENCODING, codec.token().parse().unwrap());
+    append_vary(&mut resp);
+
+    let (parts, body) = resp.into_parts();
+    Response::from_parts(parts, Body::from_stream(Encoder::new(codec, body)))
+}
+
+// Append "Accept-Encoding" to the Vary header (creating it if needed).
+fn append_vary(resp: &mut Response<Body>) {
+    let headers = resp.headers_mut();
+    let value = match headers.get(header::VARY).and_then(|v| v.to_str().ok()) {
+        Some(existing) if !existing.is_empty() => format!("{}, Accept-Encoding", existing),
+        _ => "Accept-Encoding".to_string(),
+    };
+    headers.insert(header::VARY, value.parse().unwrap());
+}
+
+// Parse Accept-Encoding, honouring q-values, and return the best codec that
+// both the client accepts and `mode` permits. Tokens other than `gzip` and
+// `deflate` (including `identity` and `*`) are skipped, so identity is never
+// rejected here: when no codec matches the body is sent uncompressed.
+fn pick_codec(mode: Compression, accept_encoding: Option<&str>) -> Option<Codec> {
+    let header = accept_encoding?;
+
+    let mut best: Option<(Codec, f32)> = None;
+    for part in header.split(',') {
+        let mut it = part.split(';');
+        let token = it.next()?.trim().to_ascii_lowercase();
+        let mut q = 1.0f32;
+        for param in it {
+            let param = param.trim();
+            if let Some(v) = param.strip_prefix("q=") {
+                q = v.parse().unwrap_or(0.0);
+            }
+        }
+        if q <= 0.0 {
+            continue;
+        }
+        let codec = match token.as_str() {
+            "gzip" if mode_allows(mode, Codec::Gzip) => Codec::Gzip,
+            "deflate" if mode_allows(mode, Codec::Deflate) => Codec::Deflate,
+            _ => continue,
+        };
+        match best {
+            Some((_, bq)) if bq >= q => {},
+            _ => best = Some((codec, q)),
+        }
+    }
+    best.map(|(c, _)| c)
+}
+
+fn mode_allows(mode: Compression, codec: Codec) -> bool {
+    match (mode, codec) {
+        (Compression::Auto, _) => true,
+        (Compression::Gzip, Codec::Gzip) => true,
+        (Compression::Deflate, Codec::Deflate) => true,
+        _ => false,
+    }
+}
+
+// Streaming encoder wrapping an inner body stream. Each inbound chunk is fed
+// to the flate2 writer and whatever it flushes out is yielded downstream; the
+// encoder is finalized when the inner stream ends.
+struct Encoder<S> {
+    inner: S,
+    state: EncState,
+    done: bool,
+}
+
+enum EncState {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl<S> Encoder<S> {
+    fn new(codec: Codec, inner: S) -> Encoder<S> {
+        let state = match codec {
+            Codec::Gzip => EncState::Gzip(GzEncoder::new(Vec::new(), FlateLevel::default())),
+            Codec::Deflate => {
+                EncState::Deflate(DeflateEncoder::new(Vec::new(), FlateLevel::default()))
+            },
+        };
+        Encoder {
+            inner,
+            state,
+            done: false,
+        }
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let buf = match &mut self.state {
+            EncState::Gzip(e) => {
+                e.write_all(chunk)?;
+                e.flush()?;
+                e.get_mut()
+            },
+            EncState::Deflate(e) => {
+                e.write_all(chunk)?;
+                e.flush()?;
+                e.get_mut()
+            },
+        };
+        Ok(Bytes::from(std::mem::take(buf)))
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        let buf = match &mut self.state {
+            EncState::Gzip(e) => {
+                e.try_finish()?;
+                e.get_mut()
+            },
+            EncState::Deflate(e) => {
+                e.try_finish()?;
+                e.get_mut()
+            },
+        };
+        Ok(Bytes::from(std::mem::take(buf)))
+    }
+}
+
+impl<S, E> Stream for Encoder<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    let out = this.write_chunk(&chunk)?;
+                    if out.is_empty() {
+                        // Nothing flushed yet, pull the next input chunk.
+                        continue;
+                    }
+                    return Poll::Ready(Some(Ok(out)));
+                },
+                Poll::Ready(Some(Err(_))) => {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "compression: upstream body error",
+                    ))));
+                },
+                Poll::Ready(None) => {
+                    this.done = true;
+                    let tail = this.finish()?;
+                    if tail.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(tail)));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_first_on_equal_q() {
+        assert_eq!(pick_codec(Compression::Auto, Some("gzip, deflate")), Some(Codec::Gzip));
+    }
+
+    #[test]
+    fn honours_q_value_ordering() {
+        assert_eq!(
+            pick_codec(Compression::Auto, Some("gzip;q=0.5, deflate;q=0.9")),
+            Some(Codec::Deflate)
+        );
+    }
+
+    #[test]
+    fn rejects_zero_q() {
+        assert_eq!(pick_codec(Compression::Auto, Some("gzip;q=0")), None);
+    }
+
+    #[test]
+    fn respects_mode_restriction() {
+        assert_eq!(pick_codec(Compression::Gzip, Some("deflate")), None);
+        assert_eq!(pick_codec(Compression::Gzip, Some("gzip, deflate")), Some(Codec::Gzip));
+    }
+
+    #[test]
+    fn no_header_means_no_codec() {
+        assert_eq!(pick_codec(Compression::Auto, None), None);
+    }
+}
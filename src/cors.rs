@@ -0,0 +1,243 @@
+//
+// Cross-Origin Resource Sharing support for browser-based WebDAV clients
+// served from another origin. Preflights short-circuit; other responses are
+// decorated with the actual-request CORS headers.
+//
+use std::collections::HashSet;
+
+use http::{header, HeaderMap, Method as HttpMethod, Response, StatusCode};
+
+use crate::body::Body;
+
+/// A CORS policy, attached to the handler through `DavConfig::cors(...)`.
+///
+/// By default nothing is allowed; use the builder methods to widen the
+/// policy. Origins in the allowed set are reflected as is. With
+/// `allow_any_origin` every other `Origin` is allowed too, answered with `*`,
+/// or reflected singly when credentials are enabled.
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    allow_any_origin: bool,
+    origins:          HashSet<String>,
+    methods:          HashSet<String>,
+    headers:          HashSet<String>,
+    expose:           Vec<String>,
+    allow_credentials: bool,
+    max_age:          Option<u64>,
+}
+
+impl CorsConfig {
+    /// A fresh, deny-all policy.
+    pub fn new() -> CorsConfig {
+        CorsConfig::default()
+    }
+
+    /// Allow any origin. When credentials are allowed the request's single
+    /// origin is reflected instead of `*`, per the CORS spec.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.allow_any_origin = true;
+        self
+    }
+
+    /// Add an allowed origin (e.g. `https://app.example.com`).
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.insert(origin.into());
+        self
+    }
+
+    /// Add an allowed request method.
+    pub fn allow_method(mut self, method: impl Into<String>) -> Self {
+        self.methods.insert(method.into().to_ascii_uppercase());
+        self
+    }
+
+    /// Add an allowed request header.
+    pub fn allow_header(mut self, header: impl Into<String>) -> Self {
+        self.headers.insert(header.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Add a response header to expose to the client.
+    pub fn expose_header(mut self, header: impl Into<String>) -> Self {
+        self.expose.push(header.into());
+        self
+    }
+
+    /// Allow credentialed requests. Forces origins to be reflected singly.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.allow_credentials = allow;
+        self
+    }
+
+    /// Set the `Access-Control-Max-Age` preflight cache duration, in seconds.
+    pub fn max_age(mut self, secs: u64) -> Self {
+        self.max_age = Some(secs);
+        self
+    }
+
+    // The value to echo back in Access-Control-Allow-Origin for `origin`.
+    // When credentials are allowed we must reflect the single origin rather
+    // than returning `*`.
+    fn allow_origin_value(&self, origin: &str) -> Option<String> {
+        if self.origins.contains(origin) {
+            return Some(origin.to_string());
+        }
+        if self.allow_any_origin {
+            if self.allow_credentials {
+                return Some(origin.to_string());
+            }
+            return Some("*".to_string());
+        }
+        None
+    }
+}
+
+// The outcome of inspecting an incoming request against the CORS policy.
+pub(crate) enum CorsDecision {
+    /// Not a CORS request (no Origin), or no policy: carry on unchanged.
+    NotCors,
+    /// A preflight request that should short-circuit with this response.
+    Preflight(Response<Body>),
+    /// An actual request from `origin`; decorate the eventual response.
+    Actual(String),
+}
+
+/// Inspect a request's headers against the policy and decide what to do.
+///
+/// A request is a preflight when the method is `OPTIONS` and an
+/// `Access-Control-Request-Method` header is present.
+pub(crate) fn inspect(cfg: &CorsConfig, headers: &HeaderMap, method: &HttpMethod) -> CorsDecision {
+    let origin = match headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) {
+        Some(o) => o.to_string(),
+        None => return CorsDecision::NotCors,
+    };
+
+    let is_preflight =
+        method == HttpMethod::OPTIONS && headers.contains_key("access-control-request-method");
+
+    if is_preflight {
+        return CorsDecision::Preflight(preflight(cfg, headers, &origin));
+    }
+
+    CorsDecision::Actual(origin)
+}
+
+// Build the response for a CORS preflight.
+fn preflight(cfg: &CorsConfig, headers: &HeaderMap, origin: &str) -> Response<Body> {
+    let allow_origin = match cfg.allow_origin_value(origin) {
+        Some(v) => v,
+        None => return forbid(),
+    };
+
+    // Requested method must be allowed.
+    let req_method = headers
+        .get("access-control-request-method")
+        .and_then(|v| v.to_str().ok())
+        .map(|m| m.to_ascii_uppercase());
+    match req_method {
+        Some(m) if cfg.methods.contains(&m) => {},
+        _ => return forbid(),
+    }
+
+    // Every requested header must be allowed.
+    if let Some(reqh) = headers
+        .get("access-control-request-headers")
+        .and_then(|v| v.to_str().ok())
+    {
+        for h in reqh.split(',') {
+            let h = h.trim().to_ascii_lowercase();
+            if !h.is_empty() && !cfg.headers.contains(&h) {
+                return forbid();
+            }
+        }
+    }
+
+    let mut resp = Response::builder().status(StatusCode::NO_CONTENT);
+    let h = resp.headers_mut().unwrap();
+    h.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin.parse().unwrap());
+    if !cfg.methods.is_empty() {
+        let methods = cfg.methods.iter().cloned().collect::<Vec<_>>().join(", ");
+        h.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods.parse().unwrap());
+    }
+    if !cfg.headers.is_empty() {
+        let hdrs = cfg.headers.iter().cloned().collect::<Vec<_>>().join(", ");
+        h.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, hdrs.parse().unwrap());
+    }
+    if cfg.allow_credentials {
+        h.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true".parse().unwrap());
+    }
+    if let Some(age) = cfg.max_age {
+        h.insert(header::ACCESS_CONTROL_MAX_AGE, age.to_string().parse().unwrap());
+    }
+    h.insert(header::VARY, "Origin".parse().unwrap());
+    h.insert(header::CONTENT_LENGTH, "0".parse().unwrap());
+    resp.body(Body::empty()).unwrap()
+}
+
+// A disallowed preflight gets a 403, not a generic OPTIONS body.
+fn forbid() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_LENGTH, "0")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Decorate a real (non-preflight) response with actual-request CORS headers.
+pub(crate) fn decorate(cfg: &CorsConfig, origin: &str, resp: &mut Response<Body>) {
+    let allow_origin = match cfg.allow_origin_value(origin) {
+        Some(v) => v,
+        None => return,
+    };
+    let h = resp.headers_mut();
+    h.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin.parse().unwrap());
+    if cfg.allow_credentials {
+        h.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true".parse().unwrap());
+    }
+    if !cfg.expose.is_empty() {
+        h.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            cfg.expose.join(", ").parse().unwrap(),
+        );
+    }
+    // Reflected origins vary per request origin.
+    append_vary_origin(h);
+}
+
+fn append_vary_origin(headers: &mut HeaderMap) {
+    let value = match headers.get(header::VARY).and_then(|v| v.to_str().ok()) {
+        Some(existing) if !existing.is_empty() => format!("{}, Origin", existing),
+        _ => "Origin".to_string(),
+    };
+    headers.insert(header::VARY, value.parse().unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_origin_is_reflected() {
+        let cfg = CorsConfig::new().allow_origin("https://app.example.com");
+        assert_eq!(
+            cfg.allow_origin_value("https://app.example.com").as_deref(),
+            Some("https://app.example.com")
+        );
+        assert_eq!(cfg.allow_origin_value("https://evil.example.com"), None);
+    }
+
+    #[test]
+    fn any_origin_without_credentials_is_wildcard() {
+        let cfg = CorsConfig::new().allow_any_origin();
+        assert_eq!(cfg.allow_origin_value("https://whatever").as_deref(), Some("*"));
+    }
+
+    #[test]
+    fn any_origin_with_credentials_reflects_single_origin() {
+        let cfg = CorsConfig::new().allow_any_origin().allow_credentials(true);
+        assert_eq!(
+            cfg.allow_origin_value("https://whatever").as_deref(),
+            Some("https://whatever")
+        );
+    }
+}
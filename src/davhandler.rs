@@ -5,14 +5,19 @@
 use std::error::Error as StdError;
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::{self, Bytes, buf::Buf, buf::FromBuf, buf::IntoBuf};
+use futures::future;
 use futures::stream::{Stream, StreamExt, TryStreamExt};
 use headers::HeaderMapExt;
 use http::{Request, Response, StatusCode};
 
 use crate::body::{Body, InBody};
+use crate::compression::{compress, Compression};
+use crate::cors::{self, CorsConfig, CorsDecision};
 use crate::davheaders;
+use crate::property::{Providers, PropertyProvider};
 use crate::util::{dav_method, AllowedMethods, Method};
 use crate::davpath::DavPath;
 
@@ -48,8 +53,33 @@ pub struct DavConfig {
     pub principal: Option<String>,
     /// Hide symbolic links? `None` maps to `true`.
     pub hide_symlinks: Option<bool>,
+    /// On-the-fly response body compression. `None` maps to `Compression::None`.
+    pub compression: Option<Compression>,
+    /// Don't compress response bodies smaller than this. `None` maps to 1024
+    /// bytes. A body whose length is not known up front is never compressed.
+    pub min_compress_size: Option<u64>,
+    /// CORS policy for browser-based clients. `None` disables CORS handling.
+    pub cors: Option<CorsConfig>,
+    /// Maximum size of an in-memory method body (PROPFIND/PROPPATCH/LOCK/…).
+    /// `None` maps to 65536 bytes.
+    pub max_request_body: Option<usize>,
+    /// Maximum size of a PUT/PATCH upload streamed to the filesystem.
+    /// `None` means unbounded.
+    pub max_upload_size: Option<u64>,
+    /// Abort a request that takes longer than this to arrive. `None` disables
+    /// the per-request timeout.
+    pub request_timeout: Option<Duration>,
+    /// Extra property providers for PROPFIND/PROPPATCH, consulted in order.
+    pub property_providers: Providers,
 }
 
+// Fallback cap for in-memory method bodies when none is configured.
+const DEFAULT_MAX_REQUEST_BODY: usize = 65536;
+
+// Fallback minimum-compress size: below this the codec overhead and the loss
+// of a precomputed Content-Length aren't worth it.
+const DEFAULT_MIN_COMPRESS_SIZE: u64 = 1024;
+
 impl DavConfig {
     /// Create a new configuration builder.
     pub fn new() -> DavConfig {
@@ -104,6 +134,60 @@ impl DavConfig {
         this
     }
 
+    /// Compress `GET` and `PROPFIND` response bodies when the client
+    /// advertises a supported codec via `Accept-Encoding` (default is off).
+    pub fn compression(self, compression: Compression) -> Self {
+        let mut this = self;
+        this.compression = Some(compression);
+        this
+    }
+
+    /// Don't compress response bodies smaller than `size` bytes, or whose
+    /// length is not known up front (default is 1024 bytes).
+    pub fn min_compress_size(self, size: u64) -> Self {
+        let mut this = self;
+        this.min_compress_size = Some(size);
+        this
+    }
+
+    /// Attach a CORS policy so browser-based WebDAV clients served from
+    /// another origin can use the handler.
+    pub fn cors(self, cors: CorsConfig) -> Self {
+        let mut this = self;
+        this.cors = Some(cors);
+        this
+    }
+
+    /// Maximum size of an in-memory method body (default is 64 KiB).
+    pub fn max_request_body(self, size: usize) -> Self {
+        let mut this = self;
+        this.max_request_body = Some(size);
+        this
+    }
+
+    /// Maximum size of a PUT/PATCH upload (default is unbounded).
+    pub fn max_upload_size(self, size: u64) -> Self {
+        let mut this = self;
+        this.max_upload_size = Some(size);
+        this
+    }
+
+    /// Abort and return `408 Request Timeout` if a request body does not
+    /// arrive within this duration (default is no timeout).
+    pub fn request_timeout(self, timeout: Duration) -> Self {
+        let mut this = self;
+        this.request_timeout = Some(timeout);
+        this
+    }
+
+    /// Register a property provider to contribute extra live properties and
+    /// handle dead-property writes for its namespaces.
+    pub fn add_property_provider(self, provider: Arc<dyn PropertyProvider>) -> Self {
+        let mut this = self;
+        this.property_providers.push(provider);
+        this
+    }
+
     fn merge(&self, new: DavConfig) -> DavConfig {
         DavConfig {
             prefix:        new.prefix.or(self.prefix.clone()),
@@ -112,6 +196,17 @@ impl DavConfig {
             allow:         new.allow.or(self.allow.clone()),
             principal:     new.principal.or(self.principal.clone()),
             hide_symlinks: new.hide_symlinks.or(self.hide_symlinks.clone()),
+            compression:   new.compression.or(self.compression),
+            min_compress_size: new.min_compress_size.or(self.min_compress_size),
+            cors:          new.cors.or_else(|| self.cors.clone()),
+            max_request_body: new.max_request_body.or(self.max_request_body),
+            max_upload_size:  new.max_upload_size.or(self.max_upload_size),
+            request_timeout:  new.request_timeout.or(self.request_timeout),
+            property_providers: if new.property_providers.is_empty() {
+                self.property_providers.clone()
+            } else {
+                new.property_providers
+            },
         }
     }
 }
@@ -127,6 +222,13 @@ pub(crate) struct DavInner {
     pub allow:         Option<AllowedMethods>,
     pub principal:     Option<String>,
     pub hide_symlinks: Option<bool>,
+    pub compression:   Compression,
+    pub min_compress_size: u64,
+    pub cors:          Option<CorsConfig>,
+    pub max_request_body: usize,
+    pub max_upload_size:  Option<u64>,
+    pub request_timeout:  Option<Duration>,
+    pub property_providers: Providers,
 }
 
 impl From<DavConfig> for DavInner {
@@ -138,6 +240,13 @@ impl From<DavConfig> for DavInner {
             allow:         cfg.allow,
             principal:     cfg.principal,
             hide_symlinks: cfg.hide_symlinks,
+            compression:   cfg.compression.unwrap_or_default(),
+            min_compress_size: cfg.min_compress_size.unwrap_or(DEFAULT_MIN_COMPRESS_SIZE),
+            cors:          cfg.cors,
+            max_request_body: cfg.max_request_body.unwrap_or(DEFAULT_MAX_REQUEST_BODY),
+            max_upload_size:  cfg.max_upload_size,
+            request_timeout:  cfg.request_timeout,
+            property_providers: cfg.property_providers,
         }
     }
 }
@@ -155,6 +264,13 @@ impl From<&DavConfig> for DavInner {
             allow:         cfg.allow,
             principal:     cfg.principal.clone(),
             hide_symlinks: cfg.hide_symlinks.clone(),
+            compression:   cfg.compression.unwrap_or_default(),
+            min_compress_size: cfg.min_compress_size.unwrap_or(DEFAULT_MIN_COMPRESS_SIZE),
+            cors:          cfg.cors.clone(),
+            max_request_body: cfg.max_request_body.unwrap_or(DEFAULT_MAX_REQUEST_BODY),
+            max_upload_size:  cfg.max_upload_size,
+            request_timeout:  cfg.request_timeout,
+            property_providers: cfg.property_providers.clone(),
         }
     }
 }
@@ -168,6 +284,13 @@ impl Clone for DavInner {
             allow:         self.allow.clone(),
             principal:     self.principal.clone(),
             hide_symlinks: self.hide_symlinks.clone(),
+            compression:   self.compression,
+            min_compress_size: self.min_compress_size,
+            cors:          self.cors.clone(),
+            max_request_body: self.max_request_body,
+            max_upload_size:  self.max_upload_size,
+            request_timeout:  self.request_timeout,
+            property_providers: self.property_providers.clone(),
         }
     }
 }
@@ -277,6 +400,15 @@ impl DavHandler {
     }
 }
 
+// Does this request carry `Expect: 100-continue`?
+fn expects_continue(req: &Request<()>) -> bool {
+    req.headers()
+        .get(http::header::EXPECT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().eq_ignore_ascii_case("100-continue"))
+        .unwrap_or(false)
+}
+
 impl DavInner {
     // helper.
     pub(crate) async fn has_parent<'a>(&'a self, path: &'a DavPath) -> bool {
@@ -284,6 +416,48 @@ impl DavInner {
         self.fs.metadata(&p).await.map(|m| m.is_dir()).unwrap_or(false)
     }
 
+    // Evaluate the header-only preconditions for a PUT/PATCH before its body is
+    // read, in response to an `Expect: 100-continue`. Returns `Ok(())` when the
+    // request should proceed, or the final error status (`409`, `423`, or that
+    // of a failed `If` precondition) when it must be rejected outright. The
+    // `413` for an oversized `Content-Length` is decided by the caller.
+    //
+    // This only decides *whether* the body may be sent. Nothing in this crate
+    // emits the interim `100 Continue` status, and `InBody` has no hook for it;
+    // the body is first read only after this check has returned `Ok(())`.
+    pub(crate) async fn check_expect_continue<'a>(
+        &'a self,
+        req: &'a Request<()>,
+        path: &'a DavPath,
+    ) -> DavResult<()> {
+        // The parent collection must already exist.
+        if !self.has_parent(path).await {
+            return Err(StatusCode::CONFLICT.into());
+        }
+
+        // If the target (or its parent) is locked by another principal we
+        // cannot accept the body. Honour any lock tokens carried in `If`.
+        if let Some(ref locksystem) = self.ls {
+            let meta = self.fs.metadata(path).await.ok();
+            let tokens = crate::conditional::if_match_get_tokens(
+                req,
+                meta.as_ref(),
+                &self.fs,
+                &self.ls,
+                path,
+            )
+            .await
+            .map_err(DavError::Status)?;
+            let t = tokens.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+            let principal = self.principal.as_ref().map(|s| s.as_str());
+            if locksystem.check(path, principal, false, false, t).is_err() {
+                return Err(StatusCode::LOCKED.into());
+            }
+        }
+
+        Ok(())
+    }
+
     // helper.
     pub(crate) fn path(&self, req: &Request<()>) -> DavPath {
         // This never fails (has been checked before)
@@ -320,10 +494,12 @@ impl DavInner {
     {
         let mut data = Vec::new();
         pin_utils::pin_mut!(body);
-        while let Some(res) = body.next().await {
-            let chunk = res.map_err(|_| {
-                DavError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "UnexpectedEof"))
-            })?;
+        let deadline = self.request_timeout.map(|t| std::time::Instant::now() + t);
+        loop {
+            let chunk = match self.next_chunk(&mut body, deadline).await? {
+                Some(chunk) => chunk,
+                None => break,
+            };
             if data.len() + chunk.len() > max_size {
                 return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
             }
@@ -332,6 +508,40 @@ impl DavInner {
         Ok(data)
     }
 
+    // Pull the next body chunk, enforcing the slow-request `deadline` (the
+    // whole body must have arrived by then, not just each chunk).
+    // A `None` return means the stream ended; a timeout maps to a hard
+    // `408 Request Timeout` and closes the connection.
+    pub(crate) async fn next_chunk<S, ReqError>(
+        &self,
+        body: &mut std::pin::Pin<&mut S>,
+        deadline: Option<std::time::Instant>,
+    ) -> DavResult<Option<Bytes>>
+    where
+        S: Stream<Item = Result<Bytes, ReqError>> + Send,
+        ReqError: StdError + Send + Sync + 'static,
+    {
+        let next = body.next();
+        let res = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(std::time::Instant::now());
+                match future::select(next, futures_timer::Delay::new(left)).await {
+                    future::Either::Left((res, _)) => res,
+                    future::Either::Right(_) => {
+                        return Err(DavError::StatusClose(StatusCode::REQUEST_TIMEOUT));
+                    },
+                }
+            },
+            None => next.await,
+        };
+        match res {
+            Some(res) => res.map(Some).map_err(|_| {
+                DavError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "UnexpectedEof"))
+            }),
+            None => Ok(None),
+        }
+    }
+
     // internal dispatcher.
     async fn handle<ReqBody, ReqError>(self, req: Request<()>, body: ReqBody) -> io::Result<Response<Body>>
     where
@@ -345,11 +555,24 @@ impl DavInner {
             .map(|s| s.contains("Microsoft"))
             .unwrap_or(false);
 
+        // CORS: short-circuit preflights and remember the origin here, so that
+        // every response — including the error responses synthesized below —
+        // carries the actual-request CORS headers.
+        let cors = self.cors.clone();
+        let cors_origin = match cors.as_ref() {
+            Some(cfg) => match cors::inspect(cfg, req.headers(), req.method()) {
+                CorsDecision::Preflight(resp) => return Ok(resp),
+                CorsDecision::Actual(origin) => Some(origin),
+                CorsDecision::NotCors => None,
+            },
+            None => None,
+        };
+
         // Turn any DavError results into a HTTP error response.
-        match self.handle2(req, body).await {
+        let mut resp = match self.handle2(req, body).await {
             Ok(resp) => {
                 debug!("== END REQUEST result OK");
-                Ok(resp)
+                resp
             },
             Err(err) => {
                 debug!("== END REQUEST result {:?}", err);
@@ -374,10 +597,14 @@ impl DavInner {
                 if err.must_close() {
                     resp.header("connection", "close");
                 }
-                let resp = resp.body(Body::empty()).unwrap();
-                Ok(resp)
+                resp.body(Body::empty()).unwrap()
             },
+        };
+
+        if let (Some(cfg), Some(origin)) = (cors.as_ref(), cors_origin.as_ref()) {
+            cors::decorate(cfg, origin, &mut resp);
         }
+        Ok(resp)
     }
 
     // internal dispatcher part 2.
@@ -430,16 +657,45 @@ impl DavInner {
         // make sure the request path is valid.
         let path = DavPath::from_uri(req.uri(), &self.prefix)?;
 
+        // If the client promised an `Expect: 100-continue`, evaluate the
+        // preconditions we can judge from the headers alone *before* the body
+        // is consumed, so a gigabyte upload isn't drained only to be rejected.
+        // Emitting the interim `100 Continue` itself is left to the body/IO
+        // glue; on failure we return the final status right away.
+        if let Method::Put | Method::Patch = method {
+            // Fast-reject when the advertised Content-Length already blows the
+            // upload limit, regardless of `Expect`. This is only an early out:
+            // a chunked upload with no (or a lying) Content-Length slips past
+            // here and is capped mid-stream by the body reader, which is handed
+            // `self.max_upload_size` when `handle_put` is dispatched below.
+            if let Some(max) = self.max_upload_size {
+                let len = req
+                    .headers()
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok());
+                if let Some(len) = len {
+                    if len > max {
+                        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+                    }
+                }
+            }
+            if expects_continue(&req) {
+                self.check_expect_continue(&req, &path).await?;
+            }
+        }
+
         // PUT is the only handler that reads the body itself. All the
         // other handlers either expected no body, or a pre-read Vec<u8>.
         let (body_strm, body_data) = match method {
             Method::Put | Method::Patch => (Some(body), Vec::new()),
-            _ => (None, self.read_request(body, 65536).await?),
+            _ => (None, self.read_request(body, self.max_request_body).await?),
         };
 
         // Not all methods accept a body.
         match method {
-            Method::Put | Method::Patch | Method::PropFind | Method::PropPatch | Method::Lock => {},
+            Method::Put | Method::Patch | Method::PropFind | Method::PropPatch | Method::Lock
+            | Method::Report | Method::Bind | Method::Unbind | Method::Rebind => {},
             _ => {
                 if body_data.len() > 0 {
                     return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into());
@@ -451,16 +707,61 @@ impl DavInner {
 
         let res = match method {
             Method::Options => self.handle_options(&req).await,
-            Method::PropFind => self.handle_propfind(&req, &body_data).await,
-            Method::PropPatch => self.handle_proppatch(&req,&body_data).await,
+            Method::PropFind => {
+                self.handle_propfind(&req, &body_data, &self.property_providers).await
+            },
+            Method::Report => self.handle_report(&req, &body_data).await,
+            Method::PropPatch => {
+                self.handle_proppatch(&req, &body_data, &self.property_providers).await
+            },
             Method::MkCol => self.handle_mkcol(&req).await,
             Method::Delete => self.handle_delete(&req).await,
             Method::Lock => self.handle_lock(&req, &body_data).await,
             Method::Unlock => self.handle_unlock(&req).await,
             Method::Head | Method::Get => self.handle_get(&req).await,
-            Method::Put | Method::Patch => self.handle_put(&req, &mut body_strm.unwrap()).await,
+            Method::Put | Method::Patch => {
+                // PUT/PATCH read the body themselves and so never pass through
+                // `next_chunk`. Enforce `request_timeout` as a whole-request
+                // deadline around the upload, so a slow (or byte-at-a-time
+                // trickling) client cannot hold the handler open indefinitely.
+                let mut strm = body_strm.unwrap();
+                match self.request_timeout {
+                    Some(timeout) => {
+                        let put = self.handle_put(&req, &mut strm, self.max_upload_size);
+                        pin_utils::pin_mut!(put);
+                        match future::select(put, futures_timer::Delay::new(timeout)).await {
+                            future::Either::Left((res, _)) => res,
+                            future::Either::Right(_) => {
+                                Err(DavError::StatusClose(StatusCode::REQUEST_TIMEOUT))
+                            },
+                        }
+                    },
+                    None => self.handle_put(&req, &mut strm, self.max_upload_size).await,
+                }
+            },
             Method::Copy | Method::Move => self.handle_copymove(&req, method).await,
+            Method::Bind => self.handle_bind(&req, &body_data).await,
+            Method::Unbind => self.handle_unbind(&req, &body_data).await,
+            Method::Rebind => self.handle_rebind(&req, &body_data).await,
         };
+
+        // Negotiate on-the-fly body compression for the metadata/content
+        // handlers that emit a body. HEAD, PROPPATCH, LOCK &c. carry no
+        // compressible payload so they are left alone, as are range requests.
+        let res = match method {
+            Method::Get | Method::PropFind if self.compression != Compression::None => {
+                let had_range = req.headers().contains_key(http::header::RANGE);
+                let accept = req
+                    .headers()
+                    .get(http::header::ACCEPT_ENCODING)
+                    .and_then(|v| v.to_str().ok());
+                res.map(|resp| {
+                    compress(self.compression, self.min_compress_size, accept, had_range, resp)
+                })
+            },
+            _ => res,
+        };
+
         res
     }
 }
@@ -0,0 +1,184 @@
+//
+// RFC 5842 BIND/UNBIND/REBIND: multiple bindings ("hard links") to a resource.
+//
+// BIND adds a second name for an existing resource, UNBIND removes a named
+// binding from a collection (without necessarily destroying the resource), and
+// REBIND atomically moves a binding. All three apply the same If/lock-token
+// precondition checks used by MKCOL, and require the optional binding
+// capability of the filesystem backend.
+//
+use http::{Request, Response, StatusCode};
+
+use crate::body::Body;
+use crate::conditional::if_match_get_tokens;
+use crate::davpath::DavPath;
+use crate::errors::DavError;
+use crate::fs::*;
+use crate::DavResult;
+
+impl crate::DavInner {
+    // BIND: create a new binding named by the body's <segment> in the
+    // request-URI collection that points at the resource named by <href>.
+    pub(crate) async fn handle_bind(&self, req: &Request<()>, body: &[u8]) -> DavResult<Response<Body>> {
+        let parent = self.path(req);
+        let segment = extract_segment(body).ok_or(DavError::Status(StatusCode::BAD_REQUEST))?;
+        let href = extract_href(body).ok_or(DavError::Status(StatusCode::BAD_REQUEST))?;
+        let source = self.href_path(&href)?;
+
+        self.check_binding_preconditions(req, &parent).await?;
+
+        match self.fs.bind(&source, &parent, &segment).await {
+            Ok(()) => created(),
+            Err(e) => Err(map_binding_err(e)),
+        }
+    }
+
+    // UNBIND: remove the binding named by the body's <segment> from the
+    // request-URI collection. An UNBIND body carries no <href>.
+    pub(crate) async fn handle_unbind(&self, req: &Request<()>, body: &[u8]) -> DavResult<Response<Body>> {
+        let parent = self.path(req);
+        let segment = extract_segment(body).ok_or(DavError::Status(StatusCode::BAD_REQUEST))?;
+
+        self.check_binding_preconditions(req, &parent).await?;
+
+        match self.fs.unbind(&parent, &segment).await {
+            Ok(()) => no_content(),
+            Err(e) => Err(map_binding_err(e)),
+        }
+    }
+
+    // REBIND: atomically move the binding named by <href> into the request-URI
+    // collection under the new name given by the body's <segment>.
+    pub(crate) async fn handle_rebind(&self, req: &Request<()>, body: &[u8]) -> DavResult<Response<Body>> {
+        let parent = self.path(req);
+        let segment = extract_segment(body).ok_or(DavError::Status(StatusCode::BAD_REQUEST))?;
+        let href = extract_href(body).ok_or(DavError::Status(StatusCode::BAD_REQUEST))?;
+        let source = self.href_path(&href)?;
+
+        self.check_binding_preconditions(req, &parent).await?;
+
+        match self.fs.rebind(&source, &parent, &segment).await {
+            Ok(()) => no_content(),
+            Err(e) => Err(map_binding_err(e)),
+        }
+    }
+
+    // Resolve an <href> from the body to a DavPath in our namespace. A body
+    // that does not parse as a URI is a client error, not a gateway failure.
+    fn href_path(&self, href: &str) -> DavResult<DavPath> {
+        let uri = href.parse::<http::Uri>().map_err(|_| DavError::Status(StatusCode::BAD_REQUEST))?;
+        DavPath::from_uri(&uri, &self.prefix).map_err(|_| DavError::Status(StatusCode::BAD_REQUEST))
+    }
+
+    // Same If/lock-token checks MKCOL performs on the (binding) parent.
+    async fn check_binding_preconditions(&self, req: &Request<()>, parent: &DavPath) -> DavResult<()> {
+        let meta = self.fs.metadata(parent).await;
+
+        // The binding parent must exist and be a collection; a missing parent
+        // is a 409, distinct from a missing source resource (a 502) reported by
+        // the backend once we get that far.
+        match meta {
+            Ok(ref m) if m.is_dir() => {},
+            _ => return Err(DavError::Status(StatusCode::CONFLICT)),
+        }
+
+        let tokens = if_match_get_tokens(req, meta.as_ref().ok(), &self.fs, &self.ls, parent)
+            .await
+            .map_err(DavError::Status)?;
+
+        if let Some(ref locksystem) = self.ls {
+            let t = tokens.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+            let principal = self.principal.as_ref().map(|s| s.as_str());
+            if locksystem.check(parent, principal, false, false, t).is_err() {
+                return Err(DavError::Status(StatusCode::LOCKED));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Map a binding FsError to the status codes RFC 5842 prescribes.
+fn map_binding_err(e: FsError) -> DavError {
+    match e {
+        // The parent collection is verified before we get here, so a NotFound
+        // now refers to the source `<href>` resource (for UNBIND, which has no
+        // `<href>`, to the named binding): a bad gateway reference.
+        FsError::NotFound => DavError::Status(StatusCode::BAD_GATEWAY),
+        // The backend does not support bindings at all.
+        FsError::NotImplemented => DavError::Status(StatusCode::METHOD_NOT_ALLOWED),
+        FsError::Exists => DavError::Status(StatusCode::CONFLICT),
+        e => DavError::FsError(e),
+    }
+}
+
+fn created() -> DavResult<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("content-length", "0")
+        .body(Body::empty())
+        .unwrap())
+}
+
+fn no_content() -> DavResult<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("content-length", "0")
+        .body(Body::empty())
+        .unwrap())
+}
+
+// Pull the first <href>...</href> text out of the request body.
+fn extract_href(body: &[u8]) -> Option<String> {
+    extract_element(body, "href")
+}
+
+// Pull the first <segment>...</segment> text (the binding's name) out of the
+// request body.
+fn extract_segment(body: &[u8]) -> Option<String> {
+    extract_element(body, "segment")
+}
+
+// Pull the text content of the first `<{name}>...</{name}>` element, ignoring
+// any XML namespace prefix on the opening tag.
+fn extract_element(body: &[u8], name: &str) -> Option<String> {
+    let text = std::str::from_utf8(body).ok()?;
+    let needle = format!("{}>", name);
+    let open = text.find(&needle)? + needle.len();
+    let rest = &text[open..];
+    let end = rest.find("</")?;
+    let value = rest[..end].trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_mapping() {
+        assert_eq!(map_binding_err(FsError::NotFound).statuscode(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            map_binding_err(FsError::NotImplemented).statuscode(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(map_binding_err(FsError::Exists).statuscode(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn extracts_namespaced_segment_and_href() {
+        let body = br#"<D:bind xmlns:D="DAV:"><D:segment>foo.txt</D:segment>
+            <D:href>/coll/bar.txt</D:href></D:bind>"#;
+        assert_eq!(extract_segment(body).as_deref(), Some("foo.txt"));
+        assert_eq!(extract_href(body).as_deref(), Some("/coll/bar.txt"));
+    }
+
+    #[test]
+    fn missing_element_is_none() {
+        let body = br#"<D:unbind xmlns:D="DAV:"><D:segment>foo</D:segment></D:unbind>"#;
+        assert_eq!(extract_href(body), None);
+    }
+}
@@ -0,0 +1,381 @@
+//
+// The `REPORT` method (RFC 3253 §3.6) and the `DAV:sync-collection` report
+// (RFC 6578), which lets a client fetch only the members that changed since a
+// previous `sync-token` instead of repeating a full PROPFIND.
+//
+use http::{Request, Response, StatusCode};
+
+use crate::body::Body;
+use crate::davpath::DavPath;
+use crate::errors::DavError;
+use crate::fs::*;
+use crate::property::{PropFindMode, PropName, PropValue};
+use crate::DavResult;
+
+// The parsed shape of a <sync-collection> request body.
+struct SyncCollection {
+    sync_token: Option<String>,
+    // `sync-level` of "infinite" descends into sub-collections.
+    infinite:   bool,
+    props:      Vec<String>,
+}
+
+impl crate::DavInner {
+    // Dispatch point for the REPORT method. We only understand
+    // `DAV:sync-collection`; anything else is a 403 with a
+    // `supported-report` precondition, as RFC 3253 requires.
+    pub(crate) async fn handle_report(
+        &self,
+        req: &Request<()>,
+        body: &[u8],
+    ) -> DavResult<Response<Body>> {
+        let path = self.path(req);
+
+        // The report only makes sense on a collection.
+        let meta = self.fs.metadata(&path).await?;
+        if !meta.is_dir() {
+            return Err(StatusCode::FORBIDDEN.into());
+        }
+
+        let report = match parse_sync_collection(body) {
+            Some(r) => r,
+            None => {
+                let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                           <D:error xmlns:D=\"DAV:\"><D:supported-report/></D:error>\n";
+                return Ok(Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .header("content-type", "application/xml; charset=utf-8")
+                    .body(Body::from(xml.to_string()))
+                    .unwrap());
+            },
+        };
+
+        self.handle_sync_collection(&path, report).await
+    }
+
+    // Walk the collection and emit a `207 Multi-Status` describing the members
+    // that changed (or were removed) since `sync_token`, followed by a fresh
+    // opaque token. When the filesystem cannot honour the supplied token we
+    // fall back to a full re-sync, as allowed by RFC 6578 §3.6.
+    async fn handle_sync_collection(
+        &self,
+        path: &DavPath,
+        report: SyncCollection,
+    ) -> DavResult<Response<Body>> {
+        let changes = self
+            .fs
+            .get_changes(path, report.sync_token.as_deref(), report.infinite)
+            .await;
+
+        let (changes, new_token) = match changes {
+            Ok(c) => c,
+            // Token unknown / expired: fall back to a full re-sync.
+            Err(FsError::NotImplemented) | Err(FsError::Forbidden) => {
+                let full = self
+                    .fs
+                    .get_changes(path, None, report.infinite)
+                    .await
+                    .map_err(DavError::FsError)?;
+                full
+            },
+            Err(e) => return Err(DavError::FsError(e)),
+        };
+
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str("\n<D:multistatus xmlns:D=\"DAV:\">\n");
+        for change in &changes {
+            xml.push_str("  <D:response>\n");
+            xml.push_str(&format!(
+                "    <D:href>{}</D:href>\n",
+                escape(&change.href)
+            ));
+            match change.state {
+                ChangeState::Removed => {
+                    xml.push_str("    <D:status>HTTP/1.1 404 Not Found</D:status>\n");
+                },
+                ChangeState::Changed => {
+                    // RFC 6578 §3.4: carry the new property *values* so the
+                    // client need not follow up with a PROPFIND.
+                    self.append_member_propstat(&mut xml, &change.href, &report.props)
+                        .await;
+                },
+            }
+            xml.push_str("  </D:response>\n");
+        }
+        xml.push_str(&format!(
+            "  <D:sync-token>{}</D:sync-token>\n",
+            escape(&new_token)
+        ));
+        xml.push_str("</D:multistatus>\n");
+
+        let resp = Response::builder()
+            .status(StatusCode::MULTI_STATUS)
+            .header("content-type", "application/xml; charset=utf-8")
+            .body(Body::from(xml))
+            .unwrap();
+        Ok(resp)
+    }
+
+    // Render the requested properties for a changed member into `xml`, with one
+    // propstat per status: `200 OK` for the ones we could resolve, `404 Not
+    // Found` for the rest, and a provider's own status where it set one. Uses
+    // the member's live metadata so the client sees real values, not bare
+    // element names.
+    async fn append_member_propstat(&self, xml: &mut String, href: &str, props: &[String]) {
+        let path = href
+            .parse::<http::Uri>()
+            .ok()
+            .and_then(|uri| DavPath::from_uri(&uri, &self.prefix).ok());
+        let meta = match path {
+            Some(ref p) => self.fs.metadata(p).await.ok(),
+            None => None,
+        };
+
+        // Ask the registered providers for the requested properties so their
+        // values land in the body alongside the built-in live properties.
+        let provided = match (path.as_ref(), meta.as_ref()) {
+            (Some(p), Some(m)) if !self.property_providers.is_empty() => {
+                let mode = PropFindMode::Named(
+                    props
+                        .iter()
+                        .map(|name| PropName::new("DAV:", name.clone()))
+                        .collect(),
+                );
+                self.property_providers
+                    .iter()
+                    .flat_map(|prov| prov.properties(p, m.as_ref(), &mode))
+                    .collect::<Vec<PropValue>>()
+            },
+            _ => Vec::new(),
+        };
+
+        // Resolve each property to a (status, rendered element) pair. A
+        // provider that owns a property wins — including with an error status,
+        // which is preserved rather than flattened into a blanket 404.
+        let mut rendered: Vec<(StatusCode, String)> = Vec::new();
+        for prop in props {
+            if let Some(value) = provided.iter().find(|v| v.name.name == *prop) {
+                let element = if value.status.is_success() {
+                    render_prop_value(value)
+                } else {
+                    format!("<{name} xmlns=\"{ns}\"/>", name = value.name.name, ns = escape(&value.name.namespace))
+                };
+                rendered.push((value.status, element));
+                continue;
+            }
+            match meta.as_ref().and_then(|m| live_property(m.as_ref(), prop)) {
+                Some(value) => rendered.push((StatusCode::OK, value)),
+                None => rendered.push((StatusCode::NOT_FOUND, format!("<D:{}/>", escape(prop)))),
+            }
+        }
+
+        // Emit one propstat per distinct status, in first-seen order.
+        let mut statuses: Vec<StatusCode> = Vec::new();
+        for (status, _) in &rendered {
+            if !statuses.contains(status) {
+                statuses.push(*status);
+            }
+        }
+        for status in statuses {
+            xml.push_str("    <D:propstat>\n      <D:prop>\n");
+            for (s, element) in &rendered {
+                if *s == status {
+                    xml.push_str(&format!("        {}\n", element));
+                }
+            }
+            xml.push_str("      </D:prop>\n");
+            xml.push_str(&format!("      <D:status>{}</D:status>\n", status_line(status)));
+            xml.push_str("    </D:propstat>\n");
+        }
+    }
+}
+
+// Format a status for a WebDAV `<D:status>` element.
+fn status_line(status: StatusCode) -> String {
+    format!(
+        "HTTP/1.1 {} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    )
+}
+
+// Render a provider-supplied `PropValue` as a complete property element,
+// declaring the provider's namespace inline so it is self-contained.
+fn render_prop_value(value: &PropValue) -> String {
+    format!(
+        "<{name} xmlns=\"{ns}\">{body}</{name}>",
+        name = value.name.name,
+        ns = escape(&value.name.namespace),
+        body = value.xml,
+    )
+}
+
+// Render a single DAV live property for `meta` as a complete `<D:...>` element,
+// or `None` when it is not a live property we can derive from metadata (the
+// caller then reports it as `404 Not Found`).
+fn live_property(meta: &dyn DavMetaData, prop: &str) -> Option<String> {
+    match prop {
+        "getcontentlength" => Some(format!("<D:getcontentlength>{}</D:getcontentlength>", meta.len())),
+        "getlastmodified" => meta.modified().ok().map(|t| {
+            format!("<D:getlastmodified>{}</D:getlastmodified>", httpdate::fmt_http_date(t))
+        }),
+        "getetag" => meta
+            .etag()
+            .map(|e| format!("<D:getetag>{}</D:getetag>", escape(&e))),
+        "resourcetype" => Some(if meta.is_dir() {
+            "<D:resourcetype><D:collection/></D:resourcetype>".to_string()
+        } else {
+            "<D:resourcetype/>".to_string()
+        }),
+        _ => None,
+    }
+}
+
+// Minimal XML scan for the bits of <sync-collection> we need.
+fn parse_sync_collection(body: &[u8]) -> Option<SyncCollection> {
+    let text = std::str::from_utf8(body).ok()?;
+    if !text.contains("sync-collection") {
+        return None;
+    }
+    let sync_token = extract(text, "sync-token").filter(|t| !t.is_empty());
+    let infinite = extract(text, "sync-level")
+        .map(|l| l.trim().eq_ignore_ascii_case("infinite"))
+        .unwrap_or(false);
+    let props = extract_prop_names(text);
+    Some(SyncCollection {
+        sync_token,
+        infinite,
+        props,
+    })
+}
+
+// Pull the trimmed text content of the first element whose local name is
+// `name`.
+fn extract(text: &str, name: &str) -> Option<String> {
+    element_inner(text, name).map(|s| s.trim().to_string())
+}
+
+// Collect the local names of the child elements inside the <prop> element.
+fn extract_prop_names(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let inner = match element_inner(text, "prop") {
+        Some(i) => i,
+        None => return out,
+    };
+    let mut pos = 0;
+    while let Some(rel) = inner[pos..].find('<') {
+        let open = pos + rel;
+        let close = match inner[open..].find('>') {
+            Some(c) => open + c,
+            None => break,
+        };
+        let tag = &inner[open + 1..close];
+        pos = close + 1;
+        // Skip closing tags, comments, declarations and processing instructions.
+        if tag.starts_with('/') || tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        let local = tag_local_name(tag);
+        if !local.is_empty() {
+            out.push(local.to_string());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_sync_collection_is_rejected() {
+        assert!(parse_sync_collection(b"<D:propfind><D:prop/></D:propfind>").is_none());
+    }
+
+    #[test]
+    fn parses_token_level_and_props() {
+        let body = br#"<?xml version="1.0"?>
+            <D:sync-collection xmlns:D="DAV:">
+              <D:sync-token>urn:x:42</D:sync-token>
+              <D:sync-level>infinite</D:sync-level>
+              <D:prop><D:getetag/><D:getcontentlength/></D:prop>
+            </D:sync-collection>"#;
+        let parsed = parse_sync_collection(body).expect("should parse");
+        assert_eq!(parsed.sync_token.as_deref(), Some("urn:x:42"));
+        assert!(parsed.infinite);
+        assert_eq!(parsed.props, vec!["getetag".to_string(), "getcontentlength".to_string()]);
+    }
+
+    #[test]
+    fn defaults_to_finite_without_token() {
+        let body = br#"<D:sync-collection xmlns:D="DAV:"><D:prop><D:getetag/></D:prop></D:sync-collection>"#;
+        let parsed = parse_sync_collection(body).expect("should parse");
+        assert_eq!(parsed.sync_token, None);
+        assert!(!parsed.infinite);
+        assert_eq!(parsed.props, vec!["getetag".to_string()]);
+    }
+}
+
+// The local name of a tag body (the text between `<` and `>`), with any
+// namespace prefix, attributes and trailing `/` stripped.
+fn tag_local_name(tag: &str) -> &str {
+    let tag = tag.trim().trim_start_matches('/').trim();
+    let tag = tag
+        .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or(tag);
+    tag.rsplit(':').next().unwrap_or(tag)
+}
+
+// Return the inner text of the first element whose local name is `name`,
+// tolerating a namespace prefix and attributes on the tags and skipping
+// comments / processing instructions. Scoped to the fixed sync-collection
+// grammar, not a general XML parser.
+fn element_inner<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let mut pos = 0;
+    while let Some(rel) = text[pos..].find('<') {
+        let open = pos + rel;
+        let close = open + text[open..].find('>')?;
+        let tag = &text[open + 1..close];
+        pos = close + 1;
+        if tag.starts_with('/') || tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        if tag_local_name(tag) != name {
+            continue;
+        }
+        if tag.ends_with('/') {
+            return Some("");
+        }
+        // Content runs to the next close tag with the same local name.
+        let rest = &text[pos..];
+        let mut scan = 0;
+        while let Some(crel) = rest[scan..].find("</") {
+            let cstart = scan + crel;
+            let cend = cstart + rest[cstart..].find('>')?;
+            if tag_local_name(&rest[cstart + 1..cend]) == name {
+                return Some(&rest[..cstart]);
+            }
+            scan = cend + 1;
+        }
+        return None;
+    }
+    None
+}
+
+// Escape the five XML metacharacters for text/attribute content.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,88 @@
+//
+// Pluggable live/dead property providers for PROPFIND and PROPPATCH.
+//
+// By default the handler surfaces a fixed set of live properties. A
+// `PropertyProvider` registered through `DavConfig::add_property_provider(...)`
+// lets an application contribute additional namespaced properties (for example
+// the RFC 4331 quota properties, or custom application metadata) without
+// forking the crate.
+//
+use std::sync::Arc;
+
+use http::StatusCode;
+
+use crate::davpath::DavPath;
+use crate::fs::DavMetaData;
+
+/// A fully-qualified property name: an XML namespace plus a local name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropName {
+    pub namespace: String,
+    pub name:      String,
+}
+
+impl PropName {
+    /// Construct a `PropName` from a namespace and local name.
+    pub fn new(namespace: impl Into<String>, name: impl Into<String>) -> PropName {
+        PropName {
+            namespace: namespace.into(),
+            name:      name.into(),
+        }
+    }
+}
+
+/// How the PROPFIND request asked for properties.
+#[derive(Debug, Clone)]
+pub enum PropFindMode {
+    /// `<allprop>`: every property the resource has.
+    AllProp,
+    /// `<propname>`: just the names, no values.
+    PropName,
+    /// A named `<prop>` list.
+    Named(Vec<PropName>),
+}
+
+/// A property value rendered as an XML fragment, with the status that should
+/// appear in the enclosing `<propstat>`.
+pub struct PropValue {
+    pub name:   PropName,
+    /// The serialized XML content of the property element. Ignored for a
+    /// non-`2xx` status or a `propname` request.
+    pub xml:    String,
+    pub status: StatusCode,
+}
+
+/// Contributes extra properties to PROPFIND and handles PROPPATCH writes for
+/// the namespaces it owns.
+///
+/// Providers are consulted in registration order, after the built-in live
+/// properties, so partial failures map cleanly into the `207 Multi-Status`
+/// body via the per-property `status`.
+pub trait PropertyProvider: Send + Sync {
+    /// Return the additional properties this provider contributes for `path`,
+    /// honouring the requested `mode`. For `propname` only the names are used.
+    fn properties(
+        &self,
+        path: &DavPath,
+        meta: &dyn DavMetaData,
+        mode: &PropFindMode,
+    ) -> Vec<PropValue>;
+
+    /// Validate and persist a dead property set via PROPPATCH. A `value` of
+    /// `None` is a `<remove>`. Return `Some(status)` — including error statuses
+    /// like `StatusCode::FORBIDDEN` for a genuine write-denied — when this
+    /// provider owns `name`, or `None` when it does not, so the dispatcher can
+    /// try the next provider.
+    fn patch(
+        &self,
+        _path: &DavPath,
+        _name: &PropName,
+        _value: Option<&str>,
+    ) -> Option<StatusCode> {
+        // By default a provider owns no writable properties.
+        None
+    }
+}
+
+/// The ordered set of providers attached to a handler. Cheaply cloneable.
+pub(crate) type Providers = Vec<Arc<dyn PropertyProvider>>;